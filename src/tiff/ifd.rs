@@ -5,7 +5,28 @@ use std::collections::{HashMap};
 
 use super::stream::{ByteOrder, SmartReader, EndianReader};
 
-use self::Value::{Unsigned, List};
+use self::Value::{Signed, Unsigned, Rational, SRational, Float, Double, Ascii, List};
+
+fn f32_from_bits(bits: u32) -> f32 {
+    unsafe { ::std::mem::transmute(bits) }
+}
+
+fn f64_from_bits(bits: u64) -> f64 {
+    unsafe { ::std::mem::transmute(bits) }
+}
+
+/// Recombines the two 32-bit words of a DOUBLE tag value, each already decoded in
+/// the file's own byte order, into the 64-bit bit pattern. The first word read is
+/// the low-order half for a little-endian file and the high-order half for a
+/// big-endian one, matching how the file's bytes would be read as one 8-byte value.
+fn combine_double_words(first: u32, second: u32, byte_order: ByteOrder) -> u64 {
+    let first = first as u64;
+    let second = second as u64;
+    match byte_order {
+        ByteOrder::LittleEndian => (second << 32) | first,
+        ByteOrder::BigEndian => (first << 32) | second,
+    }
+}
 
 macro_rules! tags {
     {$(
@@ -220,8 +241,13 @@ pub enum Type {
 #[allow(unused_qualifications)]
 #[derive(Debug)]
 pub enum Value {
-    //Signed(i32),
+    Signed(i32),
     Unsigned(u32),
+    Rational(u32, u32),
+    SRational(i32, i32),
+    Float(f32),
+    Double(f64),
+    Ascii(String),
     List(Vec<Value>)
 }
 
@@ -252,7 +278,50 @@ impl Value {
                 Ok(new_vec)
             },
             Unsigned(val) => Ok(vec![val]),
-            //_ => Err(::image::FormatError("Tag data malformed.".to_string()))
+            val => Err(::image::ImageError::FormatError(format!(
+                "Expected unsigned integer(s), {:?} found.", val
+            )))
+        }
+    }
+    pub fn as_f64(self) -> ::image::ImageResult<f64> {
+        match self {
+            Unsigned(val) => Ok(val as f64),
+            Signed(val) => Ok(val as f64),
+            Float(val) => Ok(val as f64),
+            Double(val) => Ok(val),
+            Rational(numerator, denominator) => Ok(numerator as f64 / denominator as f64),
+            SRational(numerator, denominator) => Ok(numerator as f64 / denominator as f64),
+            val => Err(::image::ImageError::FormatError(format!(
+                "Expected a number, {:?} found.", val
+            )))
+        }
+    }
+    pub fn as_string(self) -> ::image::ImageResult<String> {
+        match self {
+            Ascii(val) => Ok(val),
+            val => Err(::image::ImageError::FormatError(format!(
+                "Expected ASCII string, {:?} found.", val
+            )))
+        }
+    }
+    pub fn as_rational_vec(self) -> ::image::ImageResult<Vec<(u32, u32)>> {
+        match self {
+            List(vec) => {
+                let mut new_vec = Vec::with_capacity(vec.len());
+                for v in vec.into_iter() {
+                    match v {
+                        Rational(n, d) => new_vec.push((n, d)),
+                        val => return Err(::image::ImageError::FormatError(format!(
+                            "Expected rational, {:?} found.", val
+                        )))
+                    }
+                }
+                Ok(new_vec)
+            },
+            Rational(n, d) => Ok(vec![(n, d)]),
+            val => Err(::image::ImageError::FormatError(format!(
+                "Expected rational, {:?} found.", val
+            )))
         }
     }
 }
@@ -298,6 +367,52 @@ impl Entry {
             // TODO check if this could give wrong results
             // at a different endianess of file/computer.
             (Type::BYTE, 1) => Ok(Unsigned(self.offset[0] as u32)),
+            (Type::BYTE, n) if n <= 4 => {
+                let mut v = Vec::with_capacity(n as usize);
+                for i in 0 .. n as usize {
+                    v.push(Unsigned(self.offset[i] as u32))
+                }
+                Ok(List(v))
+            },
+            (Type::BYTE, n) => {
+                let mut v = Vec::with_capacity(n as usize);
+                try!(decoder.goto_offset(try!(self.r(bo).read_u32())));
+                for _ in 0 .. n {
+                    v.push(Unsigned(try!(decoder.read_byte()) as u32))
+                }
+                Ok(List(v))
+            },
+            (Type::SBYTE, 1) => Ok(Signed(self.offset[0] as i8 as i32)),
+            (Type::SBYTE, n) if n <= 4 => {
+                let mut v = Vec::with_capacity(n as usize);
+                for i in 0 .. n as usize {
+                    v.push(Signed(self.offset[i] as i8 as i32))
+                }
+                Ok(List(v))
+            },
+            (Type::SBYTE, n) => {
+                let mut v = Vec::with_capacity(n as usize);
+                try!(decoder.goto_offset(try!(self.r(bo).read_u32())));
+                for _ in 0 .. n {
+                    v.push(Signed(try!(decoder.read_byte()) as i8 as i32))
+                }
+                Ok(List(v))
+            },
+            (Type::ASCII, n) => {
+                let mut bytes = vec![0u8; n as usize];
+                if n <= 4 {
+                    bytes.copy_from_slice(&self.offset[.. n as usize]);
+                } else {
+                    try!(decoder.goto_offset(try!(self.r(bo).read_u32())));
+                    for b in bytes.iter_mut() {
+                        *b = try!(decoder.read_byte());
+                    }
+                }
+                if bytes.last() == Some(&0) {
+                    bytes.pop();
+                }
+                Ok(Ascii(String::from_utf8_lossy(&bytes).into_owned()))
+            },
             (Type::SHORT, 1) => Ok(Unsigned(try!(self.r(bo).read_u16()) as u32)),
             (Type::SHORT, 2) => {
                 let mut r = self.r(bo);
@@ -322,7 +437,93 @@ impl Entry {
                     v.push(Unsigned(try!(decoder.read_long())))
                 }
                 Ok(List(v))
-            }
+            },
+            (Type::SSHORT, 1) => Ok(Signed(try!(self.r(bo).read_u16()) as i16 as i32)),
+            (Type::SSHORT, 2) => {
+                let mut r = self.r(bo);
+                Ok(List(vec![
+                    Signed(try!(r.read_u16()) as i16 as i32),
+                    Signed(try!(r.read_u16()) as i16 as i32)
+                ]))
+            },
+            (Type::SSHORT, n) => {
+                let mut v = Vec::with_capacity(n as usize);
+                try!(decoder.goto_offset(try!(self.r(bo).read_u32())));
+                for _ in 0 .. n {
+                    v.push(Signed(try!(decoder.read_short()) as i16 as i32))
+                }
+                Ok(List(v))
+            },
+            (Type::SLONG, 1) => Ok(Signed(try!(self.r(bo).read_u32()) as i32)),
+            (Type::SLONG, n) => {
+                let mut v = Vec::with_capacity(n as usize);
+                try!(decoder.goto_offset(try!(self.r(bo).read_u32())));
+                for _ in 0 .. n {
+                    v.push(Signed(try!(decoder.read_long()) as i32))
+                }
+                Ok(List(v))
+            },
+            (Type::RATIONAL, n) => {
+                let mut v = Vec::with_capacity(n as usize);
+                try!(decoder.goto_offset(try!(self.r(bo).read_u32())));
+                for _ in 0 .. n {
+                    let numerator = try!(decoder.read_long());
+                    let denominator = try!(decoder.read_long());
+                    v.push(Rational(numerator, denominator))
+                }
+                if n == 1 {
+                    match v.pop() {
+                        Some(val) => Ok(val),
+                        None => Err(::image::ImageError::FormatError("Tag data malformed.".to_string()))
+                    }
+                } else {
+                    Ok(List(v))
+                }
+            },
+            (Type::SRATIONAL, n) => {
+                let mut v = Vec::with_capacity(n as usize);
+                try!(decoder.goto_offset(try!(self.r(bo).read_u32())));
+                for _ in 0 .. n {
+                    let numerator = try!(decoder.read_long()) as i32;
+                    let denominator = try!(decoder.read_long()) as i32;
+                    v.push(SRational(numerator, denominator))
+                }
+                if n == 1 {
+                    match v.pop() {
+                        Some(val) => Ok(val),
+                        None => Err(::image::ImageError::FormatError("Tag data malformed.".to_string()))
+                    }
+                } else {
+                    Ok(List(v))
+                }
+            },
+            (Type::FLOAT, 1) => Ok(Float(f32_from_bits(try!(self.r(bo).read_u32())))),
+            (Type::FLOAT, n) => {
+                let mut v = Vec::with_capacity(n as usize);
+                try!(decoder.goto_offset(try!(self.r(bo).read_u32())));
+                for _ in 0 .. n {
+                    v.push(Float(f32_from_bits(try!(decoder.read_long()))))
+                }
+                Ok(List(v))
+            },
+            (Type::DOUBLE, n) => {
+                let mut v = Vec::with_capacity(n as usize);
+                try!(decoder.goto_offset(try!(self.r(bo).read_u32())));
+                for _ in 0 .. n {
+                    let first = try!(decoder.read_long());
+                    let second = try!(decoder.read_long());
+                    let bits = combine_double_words(first, second, bo);
+                    v.push(Double(f64_from_bits(bits)))
+                }
+                if n == 1 {
+                    match v.pop() {
+                        Some(val) => Ok(val),
+                        None => Err(::image::ImageError::FormatError("Tag data malformed.".to_string()))
+                    }
+                } else {
+                    Ok(List(v))
+                }
+            },
             _ => Err(::image::ImageError::UnsupportedError("Unsupported data type.".to_string()))
         }
     }
@@ -330,3 +531,688 @@ impl Entry {
 
 /// Type representing an Image File Directory
 pub type Directory = HashMap<Tag, Entry>;
+
+/// Lazily walks the IFD chain of a TIFF file: each IFD ends with an offset to the
+/// next one, terminated by 0. A multi-page TIFF (document pages, or reduced-
+/// resolution overviews flagged by `NewSubfileType`/`SubfileType`) stores one IFD
+/// per page, so this lets callers pick a specific page rather than being forced
+/// onto whichever IFD happens to come first.
+pub struct DirectoryIter<'a, R: 'a> {
+    decoder: &'a mut super::TIFFDecoder<R>,
+    next_offset: u32,
+    visited: ::std::collections::HashSet<u32>,
+}
+
+impl<'a, R: Read + Seek> DirectoryIter<'a, R> {
+    pub fn new(decoder: &'a mut super::TIFFDecoder<R>, first_ifd_offset: u32) -> DirectoryIter<'a, R> {
+        DirectoryIter {
+            decoder: decoder,
+            next_offset: first_ifd_offset,
+            visited: ::std::collections::HashSet::new(),
+        }
+    }
+}
+
+impl<'a, R: Read + Seek> Iterator for DirectoryIter<'a, R> {
+    type Item = ::image::ImageResult<Directory>;
+
+    fn next(&mut self) -> Option<::image::ImageResult<Directory>> {
+        if self.next_offset == 0 {
+            return None;
+        }
+        // A corrupt or malicious chain can point back to an earlier IFD offset,
+        // which would otherwise loop forever; bail out the first time that happens.
+        if !self.visited.insert(self.next_offset) {
+            self.next_offset = 0;
+            return Some(Err(::image::ImageError::FormatError(
+                "IFD chain contains a cycle.".to_string()
+            )));
+        }
+        match self.decoder.read_ifd(self.next_offset) {
+            Ok((dir, next_offset)) => {
+                self.next_offset = next_offset;
+                Some(Ok(dir))
+            },
+            Err(err) => {
+                // Stop the chain on the first parse error rather than looping
+                // forever on a corrupt offset.
+                self.next_offset = 0;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+fn require<'a>(dir: &'a Directory, tag: Tag) -> ::image::ImageResult<&'a Entry> {
+    dir.get(&tag).ok_or_else(|| ::image::ImageError::FormatError(
+        format!("Required tag {:?} missing.", tag)
+    ))
+}
+
+fn tag_u32<R: Read + Seek>(decoder: &mut super::TIFFDecoder<R>, dir: &Directory, tag: Tag)
+-> ::image::ImageResult<u32> {
+    let entry = try!(require(dir, tag));
+    let value = try!(entry.val(decoder));
+    value.as_u32()
+}
+
+fn tag_u32_vec<R: Read + Seek>(decoder: &mut super::TIFFDecoder<R>, dir: &Directory, tag: Tag)
+-> ::image::ImageResult<Vec<u32>> {
+    let entry = try!(require(dir, tag));
+    let value = try!(entry.val(decoder));
+    value.as_u32_vec()
+}
+
+/// Tiling geometry derived from `ImageWidth`/`ImageLength` and `TileWidth`/`TileLength`,
+/// per TIFF 6.0 Section 15.
+#[derive(Clone, Copy, Debug)]
+pub struct TileInfo {
+    pub tile_width: u32,
+    pub tile_length: u32,
+    pub tiles_across: u32,
+    pub tiles_down: u32,
+}
+
+impl TileInfo {
+    pub fn new(image_width: u32, image_length: u32, tile_width: u32, tile_length: u32) -> TileInfo {
+        TileInfo {
+            tile_width: tile_width,
+            tile_length: tile_length,
+            tiles_across: (image_width + tile_width - 1) / tile_width,
+            tiles_down: (image_length + tile_length - 1) / tile_length,
+        }
+    }
+
+    /// Total number of tiles covering the image, stored and indexed in row-major order.
+    pub fn tile_count(&self) -> u32 {
+        self.tiles_across * self.tiles_down
+    }
+
+    /// Pixel coordinates, within the full image, of the top-left corner of tile `index`.
+    pub fn tile_origin(&self, index: u32) -> (u32, u32) {
+        let tile_x = index % self.tiles_across;
+        let tile_y = index / self.tiles_across;
+        (tile_x * self.tile_width, tile_y * self.tile_length)
+    }
+}
+
+/// Expands a palette-color (`PhotometricInterpretation == 3`) image into RGB using
+/// the `ColorMap` tag: a flat array of `3 * 2**BitsPerSample` SHORT values, laid out
+/// as an all-red sub-array, then all-green, then all-blue, each scaled 0-65535.
+/// `indices` holds one already-unpacked sample per pixel (sub-byte `BitsPerSample`
+/// values, e.g. 1/2/4-bit, must be unpacked to one byte per sample before calling).
+pub fn expand_palette(
+    color_map: &[u32],
+    bits_per_sample: u8,
+    indices: &[u8],
+) -> ::image::ImageResult<Vec<u8>> {
+    // Palette images are indexed by at most a 16-bit sample (TIFF 6.0 ColorMap is
+    // defined up to BitsPerSample == 16); anything larger is a bad file, and
+    // shifting by it would overflow the 1usize << bits_per_sample below.
+    if bits_per_sample > 16 {
+        return Err(::image::ImageError::FormatError(format!(
+            "BitsPerSample {} is too large for a palette image.", bits_per_sample
+        )));
+    }
+    let entries = 1usize << bits_per_sample;
+    if color_map.len() != 3 * entries {
+        return Err(::image::ImageError::FormatError(format!(
+            "ColorMap has {} entries, expected {} for {}-bit samples.",
+            color_map.len(), 3 * entries, bits_per_sample
+        )));
+    }
+
+    let mut rgb = Vec::with_capacity(indices.len() * 3);
+    for &index in indices {
+        let i = index as usize;
+        if i >= entries {
+            return Err(::image::ImageError::FormatError(
+                "Palette index out of range of ColorMap.".to_string()
+            ));
+        }
+        // ColorMap entries are scaled to the full 0-65535 SHORT range;
+        // downshift to 0-255 for 8-bit RGB output.
+        rgb.push((color_map[i] >> 8) as u8);
+        rgb.push((color_map[entries + i] >> 8) as u8);
+        rgb.push((color_map[2 * entries + i] >> 8) as u8);
+    }
+    Ok(rgb)
+}
+
+/// Converts CMYK (`PhotometricInterpretation == 5`) samples to RGB. `ink_set` is the
+/// file's `InkSet` tag: for the common `InkSet == 1` (CMYK) case this applies
+/// `R = 255 - min(255, C + K)`, `G = 255 - min(255, M + K)`, `B = 255 - min(255, Y +
+/// K)`. Any other `InkSet` describes inks other than CMYK (named via `InkNames`,
+/// counted via `NumberOfInks`), which this decoder does not yet interpret, so it is
+/// rejected rather than silently mis-decoded as CMYK. `ExtraSamples` channels beyond
+/// the four process inks (associated alpha, spot inks) are copied through after the
+/// RGB triple rather than being dropped.
+pub fn expand_cmyk(ink_set: u16, cmyk: &[u8], extra_samples_per_pixel: usize) -> ::image::ImageResult<Vec<u8>> {
+    if ink_set != 1 {
+        return Err(::image::ImageError::UnsupportedError(format!(
+            "InkSet {} is not supported; only InkSet == 1 (CMYK) is decoded.", ink_set
+        )));
+    }
+
+    let samples_per_pixel = 4 + extra_samples_per_pixel;
+    if samples_per_pixel == 0 || cmyk.len() % samples_per_pixel != 0 {
+        return Err(::image::ImageError::FormatError(
+            "CMYK buffer length is not a multiple of SamplesPerPixel.".to_string()
+        ));
+    }
+
+    let pixel_count = cmyk.len() / samples_per_pixel;
+    let mut rgb = Vec::with_capacity(pixel_count * (3 + extra_samples_per_pixel));
+
+    for pixel in cmyk.chunks(samples_per_pixel) {
+        let c = pixel[0] as u32;
+        let m = pixel[1] as u32;
+        let y = pixel[2] as u32;
+        let k = pixel[3] as u32;
+
+        rgb.push((255 - ::std::cmp::min(255, c + k)) as u8);
+        rgb.push((255 - ::std::cmp::min(255, m + k)) as u8);
+        rgb.push((255 - ::std::cmp::min(255, y + k)) as u8);
+        rgb.extend_from_slice(&pixel[4 ..]);
+    }
+
+    Ok(rgb)
+}
+
+/// Reverses TIFF `Predictor == 2` (horizontal differencing) on an 8-bit-per-sample
+/// strip/tile buffer, in place: for each row, `row[i] += row[i - SamplesPerPixel]`,
+/// with the first pixel's samples left unchanged. Must be applied per row (never
+/// carrying the running sum across row boundaries) and before any photometric
+/// interpretation of the data. A `row_byte_count` of 0 (e.g. `ImageWidth == 0` or
+/// `SamplesPerPixel == 0` feeding the caller's stride) is a no-op rather than a
+/// `chunks_mut` panic.
+pub fn undo_horizontal_predictor_u8(buf: &mut [u8], row_byte_count: usize, samples_per_pixel: usize) {
+    if row_byte_count == 0 {
+        return;
+    }
+    for row in buf.chunks_mut(row_byte_count) {
+        for i in samples_per_pixel .. row.len() {
+            row[i] = row[i].wrapping_add(row[i - samples_per_pixel]);
+        }
+    }
+}
+
+/// As `undo_horizontal_predictor_u8`, for 16-bit-per-sample data. `samples_per_row`
+/// is the row stride in samples (16-bit words), not bytes. A `samples_per_row` of 0
+/// is a no-op rather than a `chunks_mut` panic.
+pub fn undo_horizontal_predictor_u16(buf: &mut [u16], samples_per_row: usize, samples_per_pixel: usize) {
+    if samples_per_row == 0 {
+        return;
+    }
+    for row in buf.chunks_mut(samples_per_row) {
+        for i in samples_per_pixel .. row.len() {
+            row[i] = row[i].wrapping_add(row[i - samples_per_pixel]);
+        }
+    }
+}
+
+/// Default `YCbCrCoefficients` (ITU-R BT.601 luma weights) used when the tag is absent.
+pub const DEFAULT_YCBCR_COEFFICIENTS: (f64, f64, f64) = (0.299, 0.587, 0.114);
+
+/// Default `ReferenceBlackWhite` (Y black, Y white, Cb black, Cb white, Cr black, Cr
+/// white) used when the tag is absent.
+pub const DEFAULT_REFERENCE_BLACK_WHITE: [f64; 6] = [0.0, 255.0, 128.0, 255.0, 128.0, 255.0];
+
+const YCBCR_FIX_SHIFT: i32 = 16;
+const YCBCR_FIX_ONE: f64 = (1i32 << YCBCR_FIX_SHIFT) as f64;
+const YCBCR_CLAMP_OFFSET: i32 = 256;
+
+fn ycbcr_fix(val: f64) -> i32 {
+    (val * YCBCR_FIX_ONE).round() as i32
+}
+
+/// Precomputed lookup tables for `PhotometricInterpretation == 6` (YCbCr) to RGB
+/// conversion, built once per image from `YCbCrCoefficients` and
+/// `ReferenceBlackWhite` so the per-pixel inner loop is only table lookups and adds.
+pub struct YCbCrLuts {
+    y_tab: [i32; 256],
+    cr_r_tab: [i32; 256],
+    cb_b_tab: [i32; 256],
+    cr_g_tab: [i32; 256],
+    cb_g_tab: [i32; 256],
+    clamp_tab: Vec<u8>,
+}
+
+impl YCbCrLuts {
+    /// `luma` is `(LumaRed, LumaGreen, LumaBlue)` from `YCbCrCoefficients` and
+    /// `reference_black_white` is the six `ReferenceBlackWhite` values. Returns a
+    /// `FormatError` if the file declares coefficients or black/white pairs that
+    /// would blow up the fixed-point math below (e.g. `LumaGreen == 0`, or a
+    /// `ReferenceBlackWhite` pair with `white <= black`), rather than letting a
+    /// corrupt/adversarial TIFF panic on an out-of-range table index.
+    pub fn new(luma: (f64, f64, f64), reference_black_white: [f64; 6]) -> ::image::ImageResult<YCbCrLuts> {
+        let (luma_red, luma_green, luma_blue) = luma;
+        if !luma_red.is_finite() || !luma_green.is_finite() || !luma_blue.is_finite()
+            || luma_red <= 0.0 || luma_red >= 1.0
+            || luma_green <= 0.0 || luma_green >= 1.0
+            || luma_blue <= 0.0 || luma_blue >= 1.0 {
+            return Err(::image::ImageError::FormatError(
+                "YCbCrCoefficients must be finite values in (0, 1).".to_string()
+            ));
+        }
+
+        let y_black = reference_black_white[0];
+        let y_white = reference_black_white[1];
+        let cb_black = reference_black_white[2];
+        let cb_white = reference_black_white[3];
+        let cr_black = reference_black_white[4];
+        let cr_white = reference_black_white[5];
+
+        for &(black, white) in &[(y_black, y_white), (cb_black, cb_white), (cr_black, cr_white)] {
+            if !black.is_finite() || !white.is_finite() || white <= black {
+                return Err(::image::ImageError::FormatError(
+                    "ReferenceBlackWhite pair must satisfy black < white.".to_string()
+                ));
+            }
+        }
+
+        let y_scale = 255.0 / (y_white - y_black);
+        let cb_scale = 127.0 / (cb_white - cb_black);
+        let cr_scale = 127.0 / (cr_white - cr_black);
+
+        // R = Y + cr_r_coeff*Cr, B = Y + cb_b_coeff*Cb, and since LumaGreen =
+        // 1 - LumaRed - LumaBlue, G = Y + cb_g_coeff*Cb + cr_g_coeff*Cr reduces to
+        // the spec's G = (Y - LumaBlue*B - LumaRed*R) / LumaGreen.
+        let cr_r_coeff = 2.0 - 2.0 * luma_red;
+        let cb_b_coeff = 2.0 - 2.0 * luma_blue;
+        let cr_g_coeff = -luma_red * cr_r_coeff / luma_green;
+        let cb_g_coeff = -luma_blue * cb_b_coeff / luma_green;
+
+        let mut y_tab = [0i32; 256];
+        let mut cr_r_tab = [0i32; 256];
+        let mut cb_b_tab = [0i32; 256];
+        let mut cr_g_tab = [0i32; 256];
+        let mut cb_g_tab = [0i32; 256];
+
+        for code in 0 .. 256usize {
+            y_tab[code] = ycbcr_fix((code as f64 - y_black) * y_scale);
+            let cb = (code as f64 - cb_black) * cb_scale;
+            let cr = (code as f64 - cr_black) * cr_scale;
+            cb_b_tab[code] = ycbcr_fix(cb_b_coeff * cb);
+            cr_r_tab[code] = ycbcr_fix(cr_r_coeff * cr);
+            cb_g_tab[code] = ycbcr_fix(cb_g_coeff * cb);
+            cr_g_tab[code] = ycbcr_fix(cr_g_coeff * cr);
+        }
+
+        // The summed fixed-point value can run a bit below 0 or above 255 before
+        // saturation; cover that margin so the clamp is itself a table lookup.
+        let clamp_tab = (-YCBCR_CLAMP_OFFSET .. 256 + YCBCR_CLAMP_OFFSET)
+            .map(|v| if v < 0 { 0 } else if v > 255 { 255 } else { v as u8 })
+            .collect();
+
+        Ok(YCbCrLuts {
+            y_tab: y_tab,
+            cr_r_tab: cr_r_tab,
+            cb_b_tab: cb_b_tab,
+            cr_g_tab: cr_g_tab,
+            cb_g_tab: cb_g_tab,
+            clamp_tab: clamp_tab,
+        })
+    }
+
+    // Sums of table entries are computed in i64 and the index is saturated into
+    // clamp_tab's bounds rather than trusted, so a pathological (but now-rejected-
+    // upstream) coefficient can no longer overflow the add or index out of range.
+    fn clamp(&self, fixed: i64) -> u8 {
+        let rounded = (fixed + (1i64 << (YCBCR_FIX_SHIFT - 1))) >> YCBCR_FIX_SHIFT;
+        let max_index = self.clamp_tab.len() as i64 - 1;
+        let index = (rounded + YCBCR_CLAMP_OFFSET as i64).max(0).min(max_index);
+        self.clamp_tab[index as usize]
+    }
+
+    /// Converts one `(Y, Cb, Cr)` triple to RGB: five table lookups and four adds,
+    /// then one clamp-table lookup per channel.
+    pub fn to_rgb(&self, y: u8, cb: u8, cr: u8) -> [u8; 3] {
+        let y_fixed = self.y_tab[y as usize] as i64;
+        let r = y_fixed + self.cr_r_tab[cr as usize] as i64;
+        let b = y_fixed + self.cb_b_tab[cb as usize] as i64;
+        let g = y_fixed + self.cb_g_tab[cb as usize] as i64 + self.cr_g_tab[cr as usize] as i64;
+        [self.clamp(r), self.clamp(g), self.clamp(b)]
+    }
+}
+
+/// Expands a YCbCr image into full-resolution RGB, replicating each chroma sample
+/// across its `horizontal_subsampling x vertical_subsampling` block of luma samples
+/// per `YCbCrSubSampling` (e.g. 2x2). Returns a `FormatError` if either subsampling
+/// factor is 0, since both are used as divisors below and a file is free to declare
+/// a bogus `YCbCrSubSampling`.
+pub fn expand_ycbcr(
+    luts: &YCbCrLuts,
+    luma: &[u8],
+    chroma_b: &[u8],
+    chroma_r: &[u8],
+    image_width: u32,
+    image_length: u32,
+    horizontal_subsampling: u32,
+    vertical_subsampling: u32,
+) -> ::image::ImageResult<Vec<u8>> {
+    if horizontal_subsampling == 0 || vertical_subsampling == 0 {
+        return Err(::image::ImageError::FormatError(
+            "YCbCrSubSampling values must be non-zero.".to_string()
+        ));
+    }
+
+    let chroma_width = (image_width + horizontal_subsampling - 1) / horizontal_subsampling;
+    let chroma_length = (image_length + vertical_subsampling - 1) / vertical_subsampling;
+
+    let required_luma = image_width as usize * image_length as usize;
+    let required_chroma = chroma_width as usize * chroma_length as usize;
+    if luma.len() < required_luma || chroma_b.len() < required_chroma || chroma_r.len() < required_chroma {
+        return Err(::image::ImageError::FormatError(
+            "Decoded YCbCr data is shorter than ImageWidth/ImageLength imply.".to_string()
+        ));
+    }
+
+    let mut rgb = vec![0u8; image_width as usize * image_length as usize * 3];
+
+    for y in 0 .. image_length {
+        for x in 0 .. image_width {
+            let chroma_x = x / horizontal_subsampling;
+            let chroma_y = y / vertical_subsampling;
+            let chroma_index = (chroma_y * chroma_width + chroma_x) as usize;
+            let luma_index = (y * image_width + x) as usize;
+
+            let pixel = luts.to_rgb(luma[luma_index], chroma_b[chroma_index], chroma_r[chroma_index]);
+            let out = luma_index * 3;
+            rgb[out .. out + 3].copy_from_slice(&pixel);
+        }
+    }
+
+    Ok(rgb)
+}
+
+/// Reads every tile referenced by `dir` and blits it into `image_data`, a
+/// `image_width * image_length * samples_per_pixel` byte buffer in row-major order.
+/// Tiles that overhang the right or bottom edge of the image are clipped, per spec.
+/// `decode_chunk` is the same per-chunk decompression strips use; it is called once
+/// per tile with that tile's offset, byte count, and a `tile_width * tile_length *
+/// samples_per_pixel` scratch buffer to fill.
+pub fn read_tiles<R, F>(
+    decoder: &mut super::TIFFDecoder<R>,
+    dir: &Directory,
+    image_width: u32,
+    image_length: u32,
+    samples_per_pixel: u32,
+    image_data: &mut [u8],
+    mut decode_chunk: F
+) -> ::image::ImageResult<()>
+where R: Read + Seek,
+      F: FnMut(&mut super::TIFFDecoder<R>, u64, u32, &mut [u8]) -> ::image::ImageResult<()> {
+    if dir.contains_key(&Tag::StripOffsets) {
+        return Err(::image::ImageError::FormatError(
+            "TIFF file specifies both strip and tile tags.".to_string()
+        ));
+    }
+
+    let tile_width = try!(tag_u32(decoder, dir, Tag::TileWidth));
+    let tile_length = try!(tag_u32(decoder, dir, Tag::TileLength));
+    if tile_width == 0 || tile_length == 0 {
+        return Err(::image::ImageError::FormatError(
+            "TileWidth and TileLength must be non-zero.".to_string()
+        ));
+    }
+    let offsets = try!(tag_u32_vec(decoder, dir, Tag::TileOffsets));
+    let byte_counts = try!(tag_u32_vec(decoder, dir, Tag::TileByteCounts));
+
+    let info = TileInfo::new(image_width, image_length, tile_width, tile_length);
+    if offsets.len() as u32 != info.tile_count() || byte_counts.len() as u32 != info.tile_count() {
+        return Err(::image::ImageError::FormatError(
+            "Number of tiles does not match TilesAcross * TilesDown.".to_string()
+        ));
+    }
+
+    let row_stride = image_width as usize * samples_per_pixel as usize;
+    let tile_row_stride = tile_width as usize * samples_per_pixel as usize;
+    let mut tile_buf = vec![0u8; tile_row_stride * tile_length as usize];
+
+    for index in 0 .. info.tile_count() {
+        let (origin_x, origin_y) = info.tile_origin(index);
+        try!(decode_chunk(
+            decoder,
+            offsets[index as usize] as u64,
+            byte_counts[index as usize],
+            &mut tile_buf
+        ));
+
+        let visible_width = ::std::cmp::min(tile_width, image_width - origin_x) as usize;
+        let visible_length = ::std::cmp::min(tile_length, image_length - origin_y) as usize;
+        let copy_len = visible_width * samples_per_pixel as usize;
+
+        for y in 0 .. visible_length {
+            let src_start = y * tile_row_stride;
+            let dst_start = (origin_y as usize + y) * row_stride
+                + origin_x as usize * samples_per_pixel as usize;
+            image_data[dst_start .. dst_start + copy_len]
+                .copy_from_slice(&tile_buf[src_start .. src_start + copy_len]);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::Value::{Signed, Unsigned, Rational, SRational, Float, Double, Ascii, List};
+
+    #[test]
+    fn as_f64_converts_every_numeric_variant() {
+        assert_eq!(Unsigned(7).as_f64().unwrap(), 7.0);
+        assert_eq!(Signed(-7).as_f64().unwrap(), -7.0);
+        assert_eq!(Float(1.5).as_f64().unwrap(), 1.5);
+        assert_eq!(Double(1.5).as_f64().unwrap(), 1.5);
+        assert_eq!(Rational(1, 2).as_f64().unwrap(), 0.5);
+        assert_eq!(SRational(-1, 2).as_f64().unwrap(), -0.5);
+        assert!(Ascii("x".to_string()).as_f64().is_err());
+    }
+
+    #[test]
+    fn as_string_round_trips_ascii() {
+        assert_eq!(Ascii("Acme Scanner".to_string()).as_string().unwrap(), "Acme Scanner");
+        assert!(Unsigned(1).as_string().is_err());
+    }
+
+    #[test]
+    fn as_rational_vec_handles_single_and_list() {
+        assert_eq!(Rational(72, 1).as_rational_vec().unwrap(), vec![(72, 1)]);
+        assert_eq!(
+            List(vec![Rational(1, 3), Rational(2, 3)]).as_rational_vec().unwrap(),
+            vec![(1, 3), (2, 3)]
+        );
+        assert!(List(vec![Unsigned(1)]).as_rational_vec().is_err());
+    }
+
+    #[test]
+    fn combine_double_words_matches_file_byte_order() {
+        // f64 1.0 is the bit pattern 0x3FF0000000000000: high word 0x3FF00000,
+        // low word 0x00000000. `decoder.read_long()` already decodes each 32-bit
+        // word per the file's own byte order, so in a big-endian file the first
+        // word read is the high half and the second is the low half; in a
+        // little-endian file it's the other way around.
+        let bits_be = combine_double_words(0x3FF00000, 0x00000000, ByteOrder::BigEndian);
+        assert_eq!(f64_from_bits(bits_be), 1.0);
+
+        let bits_le = combine_double_words(0x00000000, 0x3FF00000, ByteOrder::LittleEndian);
+        assert_eq!(f64_from_bits(bits_le), 1.0);
+    }
+
+    #[test]
+    fn tile_info_computes_ceil_division_with_overhang() {
+        // A 100x100 image tiled in 32x32 tiles needs 4 tiles in each direction,
+        // with the last row/column of tiles overhanging the image edge.
+        let info = TileInfo::new(100, 100, 32, 32);
+        assert_eq!(info.tiles_across, 4);
+        assert_eq!(info.tiles_down, 4);
+        assert_eq!(info.tile_count(), 16);
+        assert_eq!(info.tile_origin(0), (0, 0));
+        assert_eq!(info.tile_origin(1), (32, 0));
+        assert_eq!(info.tile_origin(4), (0, 32));
+        assert_eq!(info.tile_origin(15), (96, 96));
+    }
+
+    #[test]
+    fn tile_info_exact_division_has_no_overhang() {
+        let info = TileInfo::new(64, 32, 32, 32);
+        assert_eq!(info.tiles_across, 2);
+        assert_eq!(info.tiles_down, 1);
+        assert_eq!(info.tile_count(), 2);
+    }
+
+    #[test]
+    fn expand_palette_maps_indices_to_colormap_entries() {
+        // 2-bit palette: 4 entries, colors red/green/blue/white, each channel
+        // scaled to the full 0-65535 SHORT range.
+        let color_map = vec![
+            0xFFFF, 0x0000, 0x0000, 0xFFFF, // red
+            0x0000, 0xFFFF, 0x0000, 0xFFFF, // green
+            0x0000, 0x0000, 0xFFFF, 0xFFFF, // blue
+        ];
+        let rgb = expand_palette(&color_map, 2, &[0, 1, 2, 3]).unwrap();
+        assert_eq!(rgb, vec![
+            255, 0, 0,
+            0, 255, 0,
+            0, 0, 255,
+            255, 255, 255,
+        ]);
+    }
+
+    #[test]
+    fn expand_palette_rejects_mismatched_colormap_length() {
+        let color_map = vec![0u32; 3]; // too short for 2-bit (needs 3*4=12)
+        assert!(expand_palette(&color_map, 2, &[0]).is_err());
+    }
+
+    #[test]
+    fn expand_palette_rejects_index_out_of_range() {
+        let color_map = vec![0u32; 3 * 4];
+        assert!(expand_palette(&color_map, 2, &[4]).is_err());
+    }
+
+    #[test]
+    fn expand_palette_rejects_oversized_bits_per_sample() {
+        assert!(expand_palette(&[], 200, &[]).is_err());
+    }
+
+    #[test]
+    fn ycbcr_luts_rejects_invalid_coefficients() {
+        assert!(YCbCrLuts::new((0.299, 0.0, 0.114), DEFAULT_REFERENCE_BLACK_WHITE).is_err());
+        assert!(YCbCrLuts::new((1.5, 0.587, 0.114), DEFAULT_REFERENCE_BLACK_WHITE).is_err());
+    }
+
+    #[test]
+    fn ycbcr_luts_rejects_invalid_reference_black_white() {
+        let bad = [0.0, 0.0, 128.0, 255.0, 128.0, 255.0]; // Y white == Y black
+        assert!(YCbCrLuts::new(DEFAULT_YCBCR_COEFFICIENTS, bad).is_err());
+    }
+
+    #[test]
+    fn ycbcr_luts_converts_black_and_white() {
+        let luts = YCbCrLuts::new(DEFAULT_YCBCR_COEFFICIENTS, DEFAULT_REFERENCE_BLACK_WHITE).unwrap();
+        assert_eq!(luts.to_rgb(0, 128, 128), [0, 0, 0]);
+        assert_eq!(luts.to_rgb(255, 128, 128), [255, 255, 255]);
+    }
+
+    #[test]
+    fn expand_ycbcr_rejects_zero_subsampling() {
+        let luts = YCbCrLuts::new(DEFAULT_YCBCR_COEFFICIENTS, DEFAULT_REFERENCE_BLACK_WHITE).unwrap();
+        let result = expand_ycbcr(&luts, &[0, 0, 0, 0], &[128], &[128], 2, 2, 0, 2);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn expand_ycbcr_replicates_chroma_across_subsampled_block() {
+        let luts = YCbCrLuts::new(DEFAULT_YCBCR_COEFFICIENTS, DEFAULT_REFERENCE_BLACK_WHITE).unwrap();
+        // A single 2x2 luma block sharing one chroma pair, all at full white.
+        let luma = vec![255u8; 4];
+        let chroma_b = vec![128u8];
+        let chroma_r = vec![128u8];
+        let rgb = expand_ycbcr(&luts, &luma, &chroma_b, &chroma_r, 2, 2, 2, 2).unwrap();
+        assert_eq!(rgb, vec![255u8; 2 * 2 * 3]);
+    }
+
+    #[test]
+    fn expand_ycbcr_rejects_truncated_planes() {
+        let luts = YCbCrLuts::new(DEFAULT_YCBCR_COEFFICIENTS, DEFAULT_REFERENCE_BLACK_WHITE).unwrap();
+        // ImageWidth*ImageLength == 4, but only 3 luma samples are actually present
+        // (e.g. a truncated file or an undercounted StripByteCounts).
+        let luma = vec![255u8; 3];
+        let chroma_b = vec![128u8];
+        let chroma_r = vec![128u8];
+        assert!(expand_ycbcr(&luts, &luma, &chroma_b, &chroma_r, 2, 2, 2, 2).is_err());
+
+        // Luma is long enough, but the chroma planes are short.
+        let luma = vec![255u8; 4];
+        assert!(expand_ycbcr(&luts, &luma, &[], &chroma_r, 2, 2, 2, 2).is_err());
+        assert!(expand_ycbcr(&luts, &luma, &chroma_b, &[], 2, 2, 2, 2).is_err());
+    }
+
+    #[test]
+    fn undo_horizontal_predictor_u8_reconstructs_rows_independently() {
+        // Two RGB (SamplesPerPixel == 3) rows of differenced deltas; the first
+        // pixel of each row is left as-is and the running sum must not carry
+        // across the row boundary.
+        let mut buf = vec![
+            10, 20, 30,  1, 1, 1,  1, 1, 1,
+            5, 5, 5,     2, 2, 2,  2, 2, 2,
+        ];
+        undo_horizontal_predictor_u8(&mut buf, 9, 3);
+        assert_eq!(buf, vec![
+            10, 20, 30,  11, 21, 31,  12, 22, 32,
+            5, 5, 5,     7, 7, 7,     9, 9, 9,
+        ]);
+    }
+
+    #[test]
+    fn undo_horizontal_predictor_u8_wraps_on_overflow() {
+        let mut buf = vec![250u8, 10];
+        undo_horizontal_predictor_u8(&mut buf, 2, 1);
+        assert_eq!(buf, vec![250, 4]); // 250 + 10 wraps to 4
+    }
+
+    #[test]
+    fn undo_horizontal_predictor_u16_reconstructs_rows() {
+        let mut buf: Vec<u16> = vec![1000, 100, 100, 2000, 50, 50];
+        undo_horizontal_predictor_u16(&mut buf, 3, 1);
+        assert_eq!(buf, vec![1000, 1100, 1200, 2000, 2050, 2100]);
+    }
+
+    #[test]
+    fn undo_horizontal_predictor_is_a_no_op_on_zero_stride() {
+        // ImageWidth == 0 or SamplesPerPixel == 0 can feed a zero row stride in;
+        // chunks_mut(0) would otherwise panic.
+        let mut buf8 = vec![1u8, 2, 3];
+        undo_horizontal_predictor_u8(&mut buf8, 0, 1);
+        assert_eq!(buf8, vec![1, 2, 3]);
+
+        let mut buf16: Vec<u16> = vec![1, 2, 3];
+        undo_horizontal_predictor_u16(&mut buf16, 0, 1);
+        assert_eq!(buf16, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn expand_cmyk_converts_process_inks_to_rgb() {
+        // Pure cyan (C=255, others 0) should leave red fully subtracted.
+        let cmyk = vec![255, 0, 0, 0];
+        assert_eq!(expand_cmyk(1, &cmyk, 0).unwrap(), vec![0, 255, 255]);
+
+        // Full black (K=255) drives every channel to 0 regardless of the other inks.
+        let cmyk = vec![0, 0, 0, 255];
+        assert_eq!(expand_cmyk(1, &cmyk, 0).unwrap(), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn expand_cmyk_passes_through_extra_samples() {
+        let cmyk = vec![255, 0, 0, 0, 42];
+        assert_eq!(expand_cmyk(1, &cmyk, 1).unwrap(), vec![0, 255, 255, 42]);
+    }
+
+    #[test]
+    fn expand_cmyk_rejects_non_cmyk_ink_set() {
+        assert!(expand_cmyk(2, &[0, 0, 0, 0], 0).is_err());
+    }
+}